@@ -1,4 +1,7 @@
-use crate::{util, IncludedDemo};
+use crate::pool::{self, DemoOutcome};
+use crate::util::EventFilter;
+use crate::walk::FileFlags;
+use crate::IncludedDemo;
 use colored::Colorize;
 use once_cell::sync::Lazy;
 use regex::Regex;
@@ -10,69 +13,75 @@ static EVENT_EXTRACT_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r#""name":\s?+"(.
 
 const EMPTY_EVENTS: &str = r#"{"events":[]}"#;
 
-fn should_include_demo(content: &mut String, filter_ks_only: bool) -> (bool, &'static str) {
+fn should_include_demo(content: &mut String, filter: &EventFilter) -> (bool, String) {
     // Remove whitespace
     content.retain(|c| !c.is_whitespace());
 
     // No events
     if content == EMPTY_EVENTS {
-        return (true, "no events");
+        return (true, "no events".to_string());
     }
 
-    if filter_ks_only {
-        for (_, [event_type]) in EVENT_EXTRACT_RE
-            .captures_iter(content)
-            .map(|ref c| c.extract())
-        {
-            if !event_type.eq_ignore_ascii_case("killstreak") {
-                // This demo contains an event type that is not a killstreak and should not be included
-                return (false, "has custom bookmark");
-            }
+    for (_, [event_type]) in EVENT_EXTRACT_RE
+        .captures_iter(content)
+        .map(|ref c| c.extract())
+    {
+        if !filter.allows(event_type) {
+            // This demo contains an event type that isn't on the allow-list
+            return (false, format!("has disallowed event '{}'", event_type));
         }
-
-        return (true, "has only killstreak events");
     }
 
-    (false, "has events")
+    (true, "has only allowed events".to_string())
 }
 
 pub fn collect_ds_demos(
     demos_dir: &Path,
-    include_ks_only: bool,
+    event_filter: &EventFilter,
+    file_flags: &FileFlags,
+    thread_count: usize,
     included_files: &mut Vec<IncludedDemo>,
 ) -> Result<(), anyhow::Error> {
-    for entry in std::fs::read_dir(demos_dir)?
-        .map(|e| e.unwrap())
-        .filter(|e| util::is_demo(&e.path().extension()))
-    {
-        let entry_path = entry.path();
-        let file_name = entry_path.file_name().unwrap();
+    let outcomes = pool::process_demos(demos_dir, file_flags, thread_count, |entry_path| {
+        let file_name = entry_path.file_name().unwrap().to_owned();
         let json_path = entry_path.with_extension("json");
 
         if !json_path.exists() {
-            println!("Can't find json events file for demo {:?}", file_name);
-            continue;
+            return DemoOutcome::Skipped {
+                file_name,
+                reason: "can't find json events file for demo".to_string(),
+            };
         }
 
         let (should_include, reason) = match std::fs::read_to_string(&json_path) {
-            Ok(mut content) => should_include_demo(&mut content, include_ks_only),
+            Ok(mut content) => should_include_demo(&mut content, event_filter),
             Err(e) => {
-                eprintln!("Failed to read events json file {:?}: {:?}", json_path, e);
-                (false, "failed to read json")
+                return DemoOutcome::Skipped {
+                    file_name,
+                    reason: format!("failed to read events json file {:?}: {:?}", json_path, e),
+                }
             }
         };
 
         if !should_include {
-            println!("{} {:?}: {}", "Skipping".red(), file_name, reason);
-            continue;
+            return DemoOutcome::Skipped { file_name, reason };
         }
 
-        included_files.push(IncludedDemo {
+        DemoOutcome::Included(IncludedDemo {
             inclusion_reason: reason,
             demo_path: entry_path,
-            events_json_path: Some(json_path.clone()),
+            events_json_path: Some(json_path),
             id: "demosupport",
-        });
+        })
+    })?;
+
+    for outcome in outcomes {
+        match outcome {
+            DemoOutcome::Included(demo) => included_files.push(demo),
+            DemoOutcome::Skipped { file_name, reason } => {
+                println!("{} {:?}: {}", "Skipping".red(), file_name, reason)
+            }
+        }
     }
 
     Ok(())
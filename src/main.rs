@@ -1,19 +1,24 @@
 mod ds;
+mod pool;
 mod prec;
 mod util;
+mod walk;
 
 use anyhow::anyhow;
 use colored::Colorize;
 use inquire::{Confirm, MultiSelect, Select, Text};
+use std::collections::{HashMap, HashSet};
 use std::fmt::{Display, Formatter};
 use std::fs::OpenOptions;
 use std::io::{stdin, BufWriter, Read, Write};
 use std::path::{Path, PathBuf};
+use std::time::SystemTime;
 
 #[derive(Debug, Copy, Clone)]
 enum IncludedFilesAction {
     MoveCopy,
     Export,
+    Deduplicate,
 }
 
 impl Display for IncludedFilesAction {
@@ -21,12 +26,13 @@ impl Display for IncludedFilesAction {
         match self {
             Self::MoveCopy => f.write_str("Copy/Move"),
             Self::Export => f.write_str("Export paths"),
+            Self::Deduplicate => f.write_str("Deduplicate"),
         }
     }
 }
 
 pub struct IncludedDemo {
-    inclusion_reason: &'static str,
+    inclusion_reason: String,
     demo_path: PathBuf,
     // DemoSupport specific
     events_json_path: Option<PathBuf>,
@@ -35,10 +41,23 @@ pub struct IncludedDemo {
 }
 
 impl IncludedDemo {
-    pub fn move_to(&mut self, copy: bool, output_dir: &Path) -> Result<(), anyhow::Error> {
+    /// `planned_dirs` tracks the per-`id` output directories this run has
+    /// already created (or, in dry-run, already reported) so that moving many
+    /// files into the same directory only prints one "Would create" line.
+    pub fn move_to(
+        &mut self,
+        copy: bool,
+        output_dir: &Path,
+        dry_run: bool,
+        planned_dirs: &mut HashSet<PathBuf>,
+    ) -> Result<(), anyhow::Error> {
         let output_dir = output_dir.join(self.id);
-        if !output_dir.exists() {
-            std::fs::create_dir(&output_dir)?;
+        if !output_dir.exists() && planned_dirs.insert(output_dir.clone()) {
+            if dry_run {
+                println!("{}", format!("\tWould create {:?}", output_dir).italic());
+            } else {
+                std::fs::create_dir(&output_dir)?;
+            }
         }
 
         let file_op = |from: &PathBuf, to: &PathBuf| match copy {
@@ -50,12 +69,21 @@ impl IncludedDemo {
             let file_name = path.file_name().unwrap();
             let new_path = output_dir.join(file_name);
 
-            file_op(path, &new_path)?;
-
-            let verb = if copy { "Copied" } else { "Moved" };
-            println!("{}", format!("\t{} {:?}", verb, file_name).italic());
+            let verb = match (dry_run, copy) {
+                (true, true) => "Would copy",
+                (true, false) => "Would move",
+                (false, true) => "Copied",
+                (false, false) => "Moved",
+            };
+
+            if dry_run {
+                println!("{}", format!("\t{} {:?} to {:?}", verb, file_name, new_path).italic());
+            } else {
+                file_op(path, &new_path)?;
+                println!("{}", format!("\t{} {:?}", verb, file_name).italic());
+            }
 
-            if !copy {
+            if !copy && !dry_run {
                 *path = new_path;
             }
 
@@ -71,7 +99,11 @@ impl IncludedDemo {
     }
 }
 
-fn action_move_copy(demos_dir: &Path, files: &mut [IncludedDemo]) -> Result<(), anyhow::Error> {
+fn action_move_copy(
+    demos_dir: &Path,
+    files: &mut [IncludedDemo],
+    dry_run: bool,
+) -> Result<(), anyhow::Error> {
     let default_dir = demos_dir.join(util::get_output_name());
     let output_dir = Text::new("Output directory?")
         .with_default(default_dir.to_str().unwrap())
@@ -81,7 +113,11 @@ fn action_move_copy(demos_dir: &Path, files: &mut [IncludedDemo]) -> Result<(),
     let output_dir = Path::new(&output_dir);
 
     if !output_dir.exists() {
-        std::fs::create_dir(output_dir)?;
+        if dry_run {
+            println!("{}", format!("Would create {:?}", output_dir).italic());
+        } else {
+            std::fs::create_dir(output_dir)?;
+        }
     }
 
     let copy = Confirm::new("Copy files?")
@@ -90,10 +126,18 @@ fn action_move_copy(demos_dir: &Path, files: &mut [IncludedDemo]) -> Result<(),
         .prompt()
         .unwrap();
 
-    let verb = if copy { "Copied" } else { "Moved" };
+    let verb = match (dry_run, copy) {
+        (true, true) => "Would copy",
+        (true, false) => "Would move",
+        (false, true) => "Copied",
+        (false, false) => "Moved",
+    };
 
+    let mut planned_dirs = HashSet::new();
+    let mut moved_count = 0;
     for file in files.iter_mut() {
-        file.move_to(copy, output_dir)?;
+        file.move_to(copy, output_dir, dry_run, &mut planned_dirs)?;
+        moved_count += 1 + file.events_json_path.is_some() as usize;
     }
 
     println!(
@@ -101,7 +145,7 @@ fn action_move_copy(demos_dir: &Path, files: &mut [IncludedDemo]) -> Result<(),
         format!(
             "{} {} files to {}",
             verb,
-            files.len() * 2,
+            moved_count,
             output_dir.to_str().unwrap()
         )
         .bright_green()
@@ -159,6 +203,103 @@ fn action_export(demos_dir: &Path, files: &Vec<IncludedDemo>) -> Result<(), anyh
     Ok(())
 }
 
+fn action_deduplicate(
+    demos_dir: &Path,
+    files: &mut [IncludedDemo],
+    dry_run: bool,
+) -> Result<(), anyhow::Error> {
+    // Bucket by file size first - it only costs one metadata lookup per file,
+    // and lets us skip hashing the (common) case of no collisions at all.
+    let mut by_size: HashMap<u64, Vec<usize>> = HashMap::new();
+    for (index, file) in files.iter().enumerate() {
+        let size = std::fs::metadata(&file.demo_path)?.len();
+        by_size.entry(size).or_default().push(index);
+    }
+
+    let mut clusters = vec![];
+    for indices in by_size.into_values().filter(|indices| indices.len() > 1) {
+        let mut by_hash: HashMap<blake3::Hash, Vec<usize>> = HashMap::new();
+        for index in indices {
+            let bytes = std::fs::read(&files[index].demo_path)?;
+            by_hash.entry(blake3::hash(&bytes)).or_default().push(index);
+        }
+
+        clusters.extend(by_hash.into_values().filter(|indices| indices.len() > 1));
+    }
+
+    if clusters.is_empty() {
+        println!("{}", "No duplicate demos found.".bright_green());
+        return Ok(());
+    }
+
+    let mtime = |index: usize| -> SystemTime {
+        std::fs::metadata(&files[index].demo_path)
+            .and_then(|m| m.modified())
+            .unwrap_or(SystemTime::UNIX_EPOCH)
+    };
+
+    let mut duplicate_indices = vec![];
+
+    for mut cluster in clusters {
+        cluster.sort_by_key(|&index| mtime(index));
+        let (&original, dupes) = cluster.split_first().unwrap();
+
+        println!(
+            "{}",
+            format!("Duplicate cluster ({} files):", cluster.len()).bright_yellow()
+        );
+        println!(
+            "\t{:?} {}",
+            files[original].demo_path.file_name().unwrap(),
+            "(original)".italic()
+        );
+        for &dup in dupes {
+            println!("\t{:?}", files[dup].demo_path.file_name().unwrap());
+        }
+
+        duplicate_indices.extend_from_slice(dupes);
+    }
+
+    let move_duplicates = Confirm::new(&format!(
+        "Move {} duplicate file(s) to a 'duplicates' subdirectory?",
+        duplicate_indices.len()
+    ))
+    .with_default(true)
+    .prompt()
+    .unwrap();
+
+    if !move_duplicates {
+        return Ok(());
+    }
+
+    let output_dir = demos_dir.join("duplicates");
+    if !output_dir.exists() {
+        if dry_run {
+            println!("{}", format!("Would create {:?}", output_dir).italic());
+        } else {
+            std::fs::create_dir(&output_dir)?;
+        }
+    }
+
+    let mut planned_dirs = HashSet::new();
+    for &index in &duplicate_indices {
+        files[index].move_to(false, &output_dir, dry_run, &mut planned_dirs)?;
+    }
+
+    println!(
+        "{}",
+        format!(
+            "{} {} duplicate file(s) to {:?}",
+            if dry_run { "Would move" } else { "Moved" },
+            duplicate_indices.len(),
+            output_dir
+        )
+        .bright_green()
+    );
+
+    Ok(())
+}
+
 fn process() -> Result<(), anyhow::Error> {
     let demos_path = Text::new("Demos directory?").prompt().unwrap();
 
@@ -173,29 +314,93 @@ fn process() -> Result<(), anyhow::Error> {
 
     println!(
         "\
-Would you like to include demos that only contain Killstreak events?
-This will exclude demos that contain custom bookmarks (added via 'ds_mark', etc.)\
+Which event types are OK for an included demo to contain? A demo is only
+included if every event it contains matches one of these (e.g. 'killstreak',
+'bookmark', or a custom name you gave a bookmark via 'ds_mark'). Demos with
+no events are always included.\
 "
     );
 
-    let include_ks_only = Confirm::new("Include Killstreak only demos?")
-        .with_default(false)
+    let allowed_events = MultiSelect::new(
+        "Allowed event types?",
+        vec!["killstreak", "bookmark"],
+    )
+    .prompt()
+    .unwrap_or_default();
+
+    let custom_events = Text::new("Any other (custom 'ds_mark') event names to allow?")
+        .with_help_message("comma-separated - leave blank for none")
         .prompt()
-        .unwrap_or(false);
+        .unwrap_or_default();
+
+    let split_patterns = |patterns: &str| -> Vec<String> {
+        patterns
+            .split(',')
+            .map(str::trim)
+            .filter(|p| !p.is_empty())
+            .map(String::from)
+            .collect()
+    };
+
+    let event_filter = {
+        let mut allowed: Vec<String> = allowed_events.into_iter().map(String::from).collect();
+        allowed.extend(split_patterns(&custom_events));
+        util::EventFilter::new(&allowed)
+    };
+
+    let include_globs = Text::new("Include glob patterns? (comma-separated)")
+        .with_help_message("e.g. *RED_BLU*  -  leave blank to include everything")
+        .prompt()
+        .unwrap_or_default();
 
-    println!(
-        "Demos containing only Killstreak events will {}be included.",
-        if include_ks_only { "" } else { "not " }
-    );
+    let exclude_globs = Text::new("Exclude glob patterns? (comma-separated)")
+        .with_help_message("e.g. archive/**  -  leave blank to exclude nothing")
+        .prompt()
+        .unwrap_or_default();
+
+    let extensions = Text::new("Demo file extensions? (comma-separated)")
+        .with_default("dem")
+        .with_help_message("DemoSupport event files are always looked up as '.json'")
+        .prompt()
+        .unwrap_or_else(|_| "dem".to_string());
+
+    let file_flags = walk::FileFlags::new(
+        &split_patterns(&include_globs),
+        &split_patterns(&exclude_globs),
+        &split_patterns(&extensions),
+    )?;
+
+    let default_thread_count = std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1);
+
+    let thread_count = Text::new("How many worker threads?")
+        .with_default(&default_thread_count.to_string())
+        .prompt()
+        .ok()
+        .and_then(|s| s.parse::<usize>().ok())
+        .unwrap_or(default_thread_count);
 
     let mut included_demos = vec![];
 
     if demo_mode == "PREC" {
         println!("{}", "Searching for PREC demos...".bright_green());
-        prec::collect_prec_demos(demos_dir, include_ks_only, &mut included_demos)?;
+        prec::collect_prec_demos(
+            demos_dir,
+            &event_filter,
+            &file_flags,
+            thread_count,
+            &mut included_demos,
+        )?;
     } else {
         println!("{}", "Searching for DemoSupport demos...".bright_green());
-        ds::collect_ds_demos(demos_dir, include_ks_only, &mut included_demos)?;
+        ds::collect_ds_demos(
+            demos_dir,
+            &event_filter,
+            &file_flags,
+            thread_count,
+            &mut included_demos,
+        )?;
     }
 
     if included_demos.is_empty() {
@@ -212,17 +417,32 @@ This will exclude demos that contain custom bookmarks (added via 'ds_mark', etc.
         );
     }
 
+    let dry_run = Confirm::new("Dry run?")
+        .with_default(false)
+        .with_help_message("Preview what would happen without touching the filesystem")
+        .prompt()
+        .unwrap_or(false);
+
     let actions = MultiSelect::new(
         &format!("Action for {} files", included_demos.len()),
-        vec![IncludedFilesAction::MoveCopy, IncludedFilesAction::Export],
+        vec![
+            IncludedFilesAction::MoveCopy,
+            IncludedFilesAction::Export,
+            IncludedFilesAction::Deduplicate,
+        ],
     )
     .prompt()
     .unwrap();
 
     for action in actions {
         match action {
-            IncludedFilesAction::MoveCopy => action_move_copy(demos_dir, &mut included_demos)?,
+            IncludedFilesAction::MoveCopy => {
+                action_move_copy(demos_dir, &mut included_demos, dry_run)?
+            }
             IncludedFilesAction::Export => action_export(demos_dir, &included_demos)?,
+            IncludedFilesAction::Deduplicate => {
+                action_deduplicate(demos_dir, &mut included_demos, dry_run)?
+            }
         }
     }
 
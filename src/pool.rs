@@ -0,0 +1,63 @@
+use crate::walk::{self, FileFlags};
+use crate::IncludedDemo;
+use crossbeam_channel::unbounded;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::thread;
+
+/// What a worker decided about a single candidate demo.
+pub enum DemoOutcome {
+    Included(IncludedDemo),
+    Skipped { file_name: OsString, reason: String },
+}
+
+/// Walks `root` on its own thread, fanning candidate demo paths out to
+/// `thread_count` workers that each run `process`. Results are handed back as
+/// a plain `Vec` once every worker has drained its queue, so the caller is
+/// free to print "Skipping"/"Including" lines on the main thread in whatever
+/// order it likes instead of interleaving output from workers.
+pub fn process_demos<F>(
+    root: &Path,
+    flags: &FileFlags,
+    thread_count: usize,
+    process: F,
+) -> Result<Vec<DemoOutcome>, anyhow::Error>
+where
+    F: Fn(PathBuf) -> DemoOutcome + Sync,
+{
+    let (path_tx, path_rx) = unbounded::<PathBuf>();
+    let (result_tx, result_rx) = unbounded::<DemoOutcome>();
+    let walk_error = Mutex::new(None);
+
+    let results = thread::scope(|scope| {
+        for _ in 0..thread_count.max(1) {
+            let path_rx = path_rx.clone();
+            let result_tx = result_tx.clone();
+            let process = &process;
+            scope.spawn(move || {
+                for path in path_rx {
+                    let _ = result_tx.send(process(path));
+                }
+            });
+        }
+        drop(result_tx);
+
+        let walk_error = &walk_error;
+        scope.spawn(move || {
+            if let Err(e) = walk::walk_demos(root, flags, |path| {
+                let _ = path_tx.send(path);
+            }) {
+                *walk_error.lock().unwrap() = Some(e);
+            }
+        });
+
+        result_rx.iter().collect::<Vec<_>>()
+    });
+
+    if let Some(e) = walk_error.into_inner().unwrap() {
+        return Err(e);
+    }
+
+    Ok(results)
+}
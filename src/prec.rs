@@ -1,4 +1,7 @@
-use crate::{util, IncludedDemo};
+use crate::pool::{self, DemoOutcome};
+use crate::util::EventFilter;
+use crate::walk::FileFlags;
+use crate::IncludedDemo;
 use colored::Colorize;
 use once_cell::sync::Lazy;
 use regex::Regex;
@@ -10,9 +13,6 @@ use std::path::{Path, PathBuf};
 static EVENT_EXTRACT_RE: Lazy<Regex> =
     Lazy::new(|| Regex::new(r#"\[[\d/\s:]+]\s?(.+)\s\("(.+)"\s?at"#).unwrap());
 
-// This regex matches the PREC 'Kill Streak:#' event type
-static KS_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r#"Kill\sStreak:\d+"#).unwrap());
-
 const PREC_KS_FILE: &str = "KillStreaks.txt";
 
 fn find_prec_ks_file(demos_dir: &Path) -> Option<PathBuf> {
@@ -24,30 +24,27 @@ fn find_prec_ks_file(demos_dir: &Path) -> Option<PathBuf> {
     search.into_iter().flatten().find(|path| path.exists())
 }
 
-fn should_include_demo(
-    events: Option<&HashSet<&str>>,
-    filter_ks_only: bool,
-) -> (bool, &'static str) {
-    if events.is_none() {
-        return (true, "no events");
-    }
+fn should_include_demo(events: Option<&HashSet<&str>>, filter: &EventFilter) -> (bool, String) {
+    let events = match events {
+        None => return (true, "no events".to_string()),
+        Some(events) => events,
+    };
 
-    if filter_ks_only {
-        for event_name in events.unwrap() {
-            if !KS_RE.is_match(event_name) {
-                // This demo contains an event type that is not a killstreak and should not be included
-                return (false, "has custom bookmark");
-            }
+    for event_name in events {
+        if !filter.allows(event_name) {
+            // This demo contains an event type that isn't on the allow-list
+            return (false, format!("has disallowed event '{}'", event_name));
         }
-        return (true, "has only killstreak events");
     }
 
-    (false, "has events")
+    (true, "has only allowed events".to_string())
 }
 
 pub fn collect_prec_demos(
     demos_dir: &Path,
-    include_ks_only: bool,
+    event_filter: &EventFilter,
+    file_flags: &FileFlags,
+    thread_count: usize,
     included_files: &mut Vec<IncludedDemo>,
 ) -> Result<(), anyhow::Error> {
     let ks_file = match find_prec_ks_file(demos_dir) {
@@ -68,45 +65,50 @@ pub fn collect_prec_demos(
 
     let event_file_content = std::fs::read_to_string(&ks_file)?;
 
-    let mut event_map = HashMap::new();
+    // Keyed by (lowercased) file name rather than full path: the log only ever
+    // records a demo's name, never its folder, so once the walker recurses
+    // into subdirectories this is the only thing we can reliably match on. A
+    // side effect is that two same-named demos in different subfolders share
+    // one event set.
+    let mut event_map: HashMap<String, HashSet<&str>> = HashMap::new();
 
-    // Collect all referenced valid demos along with their events
+    // Collect all referenced demos along with their events
     for (_, [event_type, demo_file_name]) in EVENT_EXTRACT_RE
         .captures_iter(&event_file_content)
         .map(|ref c| c.extract())
     {
         let demo_file_name = demo_file_name.to_lowercase() + ".dem";
 
-        let demo_path = demos_dir.join(&demo_file_name);
-        if !demo_path.exists() {
-            continue;
-        }
-
-        let events = event_map.entry(demo_path).or_insert(HashSet::new());
+        let events = event_map.entry(demo_file_name).or_insert(HashSet::new());
         events.insert(event_type);
     }
 
-    for entry in std::fs::read_dir(demos_dir)?
-        .map(|e| e.unwrap())
-        .filter(|e| util::is_demo(&e.path().extension()))
-    {
-        let entry_path = entry.path();
-        let file_name = entry_path.file_name().unwrap();
+    let outcomes = pool::process_demos(demos_dir, file_flags, thread_count, |entry_path| {
+        let file_name = entry_path.file_name().unwrap().to_owned();
+        let lookup_key = file_name.to_string_lossy().to_lowercase();
 
         let (should_include, reason) =
-            should_include_demo(event_map.get(&entry_path), include_ks_only);
+            should_include_demo(event_map.get(&lookup_key), event_filter);
 
         if !should_include {
-            println!("{} {:?}: {}", "Skipping".red(), file_name, reason);
-            continue;
+            return DemoOutcome::Skipped { file_name, reason };
         }
 
-        included_files.push(IncludedDemo {
+        DemoOutcome::Included(IncludedDemo {
             inclusion_reason: reason,
             demo_path: entry_path,
             events_json_path: None,
             id: "prec",
-        });
+        })
+    })?;
+
+    for outcome in outcomes {
+        match outcome {
+            DemoOutcome::Included(demo) => included_files.push(demo),
+            DemoOutcome::Skipped { file_name, reason } => {
+                println!("{} {:?}: {}", "Skipping".red(), file_name, reason)
+            }
+        }
     }
 
     Ok(())
@@ -1,5 +1,6 @@
 use chrono::Local;
 use once_cell::sync::Lazy;
+use std::collections::HashSet;
 use std::ffi::OsStr;
 
 pub fn get_output_name() -> String {
@@ -13,7 +14,38 @@ pub fn get_output_name() -> String {
     TIME.clone()
 }
 
-pub fn is_demo(ext: &Option<&OsStr>) -> bool {
+pub fn is_demo(ext: &Option<&OsStr>, allowed_extensions: &HashSet<String>) -> bool {
     ext.and_then(OsStr::to_str)
-        .map_or(false, |str| str == "dem")
+        .map_or(false, |str| allowed_extensions.contains(&str.to_lowercase()))
+}
+
+/// A user-chosen allow-list of event kinds (killstreak, bookmark, custom
+/// `ds_mark` names, ...). A demo is kept when every event it contains matches
+/// one of these, so an empty filter allows nothing and behaves like the old
+/// "exclude anything with events" default.
+pub struct EventFilter {
+    allowed: HashSet<String>,
+}
+
+impl EventFilter {
+    pub fn new(allowed: &[String]) -> Self {
+        Self {
+            allowed: allowed.iter().map(|kind| Self::normalize(kind)).collect(),
+        }
+    }
+
+    /// Event names show up differently across demo formats (e.g. DemoSupport's
+    /// "Killstreak" vs PREC's "Kill Streak:5"), so both sides are compared after
+    /// stripping everything but letters rather than as exact strings.
+    pub fn allows(&self, event_type: &str) -> bool {
+        self.allowed.contains(&Self::normalize(event_type))
+    }
+
+    fn normalize(event_type: &str) -> String {
+        event_type
+            .chars()
+            .filter(|c| c.is_alphabetic())
+            .collect::<String>()
+            .to_lowercase()
+    }
 }
@@ -0,0 +1,137 @@
+use crate::util;
+use globset::{Glob, GlobMatcher};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+/// User-supplied include/exclude glob patterns (e.g. `*RED_BLU*`, `archive/**`),
+/// matched against each candidate path, relative to the demos root, as the
+/// directory is walked.
+pub struct FileFlags {
+    include: Vec<(String, GlobMatcher)>,
+    exclude: Vec<GlobMatcher>,
+    extensions: HashSet<String>,
+}
+
+impl FileFlags {
+    pub fn new(
+        include: &[String],
+        exclude: &[String],
+        extensions: &[String],
+    ) -> Result<Self, anyhow::Error> {
+        let compile = |patterns: &[String]| -> Result<Vec<GlobMatcher>, anyhow::Error> {
+            patterns
+                .iter()
+                .map(|pattern| Ok(Glob::new(pattern)?.compile_matcher()))
+                .collect()
+        };
+
+        let include = include
+            .iter()
+            .cloned()
+            .zip(compile(include)?)
+            .collect();
+
+        Ok(Self {
+            include,
+            exclude: compile(exclude)?,
+            extensions: extensions.iter().map(|ext| ext.to_lowercase()).collect(),
+        })
+    }
+
+    fn is_excluded(&self, path: &Path) -> bool {
+        self.exclude.iter().any(|matcher| matcher.is_match(path))
+    }
+
+    fn is_included(&self, path: &Path) -> bool {
+        self.include.is_empty() || self.include.iter().any(|(_, matcher)| matcher.is_match(path))
+    }
+
+    /// Directories worth descending into for the include patterns, so the walker
+    /// doesn't have to read every directory in the tree just to test patterns
+    /// that could never match there. The base is the directory portion of the
+    /// pattern's literal (non-glob) prefix: `archive/**` has nothing but a
+    /// trailing separator before its first wildcard, so its base is `archive`
+    /// itself; `archive/cp_*` has a partial file-name literal after the
+    /// separator, so its base is the parent directory `archive`; a pattern with
+    /// no literal prefix at all (e.g. `*RED_BLU*`) can match anywhere and falls
+    /// back to `root`.
+    fn include_bases(&self, root: &Path) -> Vec<PathBuf> {
+        if self.include.is_empty() {
+            return vec![root.to_path_buf()];
+        }
+
+        self.include
+            .iter()
+            .map(|(pattern, _)| {
+                let literal_prefix: String = pattern
+                    .chars()
+                    .take_while(|c| !matches!(c, '*' | '?' | '[' | '{'))
+                    .collect();
+
+                let base = if literal_prefix.ends_with('/') {
+                    PathBuf::from(&literal_prefix)
+                } else {
+                    Path::new(&literal_prefix)
+                        .parent()
+                        .map(Path::to_path_buf)
+                        .unwrap_or_default()
+                };
+
+                root.join(base)
+            })
+            .collect()
+    }
+}
+
+/// Recursively walks `root`, yielding every demo file (per `util::is_demo`) that
+/// passes the include/exclude patterns in `flags`. Exclude globs are tested
+/// against each path and directory as they're encountered rather than expanded
+/// up front, so a single `archive/**` exclude rule prunes that whole subtree: the
+/// `archive` directory itself is still listed once (the pattern matches only what
+/// lies beneath it), but every entry in it is skipped and nothing deeper is read.
+pub fn walk_demos(
+    root: &Path,
+    flags: &FileFlags,
+    mut visit: impl FnMut(PathBuf),
+) -> Result<(), anyhow::Error> {
+    let mut visited_dirs = HashSet::new();
+
+    for base in flags.include_bases(root) {
+        walk_dir(root, &base, flags, &mut visited_dirs, &mut visit)?;
+    }
+
+    Ok(())
+}
+
+fn walk_dir(
+    root: &Path,
+    dir: &Path,
+    flags: &FileFlags,
+    visited_dirs: &mut HashSet<PathBuf>,
+    visit: &mut impl FnMut(PathBuf),
+) -> Result<(), anyhow::Error> {
+    let relative_dir = dir.strip_prefix(root).unwrap_or(dir);
+    if !dir.is_dir() || flags.is_excluded(relative_dir) || !visited_dirs.insert(dir.to_path_buf())
+    {
+        return Ok(());
+    }
+
+    for entry in std::fs::read_dir(dir)?.map(|e| e.unwrap()) {
+        let path = entry.path();
+        let relative_path = path.strip_prefix(root).unwrap_or(path.as_path());
+
+        if flags.is_excluded(relative_path) {
+            continue;
+        }
+
+        if path.is_dir() {
+            walk_dir(root, &path, flags, visited_dirs, visit)?;
+        } else if util::is_demo(&path.extension(), &flags.extensions)
+            && flags.is_included(relative_path)
+        {
+            visit(path);
+        }
+    }
+
+    Ok(())
+}